@@ -61,6 +61,10 @@
 //!
 //! - `abort_on_release`: Sets the default behavior to abort on panic in release mode. By default, the crate halts on panic in release mode.
 //!
+//! - `nightly_message`: Includes `PanicInfo::message()` in the output written by `define_panic_with_sink!`, in
+//! addition to the panic location. Off by default for MSRV reasons, not because it needs nightly: `PanicInfo::message()`
+//! has been stable since Rust 1.81.
+//!
 //! # Note
 //!
 //! Ensure that custom panic handlers are implemented safely to avoid undefined behavior. Incorrect panic handling logic may lead to unexpected program behavior.
@@ -76,6 +80,228 @@
 //! This crate provides flexibility in defining custom panic handling behavior, empowering developers to tailor their applications' panic behavior to their specific 
 //! requirements, especially in embedded or `no_std` projects.
 
+/// Runtime support for the generated `#[panic_handler]`, shared regardless of whether it was
+/// produced by [`define_panic!`]/[`define_panic_with_sink!`] or by the `#[define_panic]` proc-macro.
+///
+/// Most items here are `#[doc(hidden)]`: internals the generated handler calls into, not part of
+/// the public API. The exceptions, part of the public API, are [`PanicAction`], and the panic
+/// hook registry ([`add_panic_hook`], [`clear_panic_hooks`], [`HookFull`], [`PanicHook`]).
+pub mod support {
+    use core::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+    /// A panic hook: a plain function pointer invoked with the [`PanicInfo`](core::panic::PanicInfo)
+    /// of the panic currently being handled, registered via [`add_panic_hook`].
+    pub type PanicHook = fn(&core::panic::PanicInfo);
+
+    /// Maximum number of hooks [`add_panic_hook`] can register at once. This crate has no allocator
+    /// to grow into, so the hook chain lives in a fixed-size array sized by this constant.
+    const HOOK_CAPACITY: usize = 4;
+
+    static PANIC_HOOKS: [AtomicPtr<()>; HOOK_CAPACITY] =
+        [const { AtomicPtr::new(core::ptr::null_mut()) }; HOOK_CAPACITY];
+    static PANIC_HOOK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Returned by [`add_panic_hook`] when all [`HOOK_CAPACITY`] slots are already taken.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct HookFull;
+
+    /// Registers `f` to run before the custom panic handler (and before the default action) on
+    /// every future panic, in addition to any hooks already registered. Hooks run in the order they
+    /// were added.
+    ///
+    /// Returns `Err(HookFull)` if all hook slots are taken; the hook is not registered in that case.
+    pub fn add_panic_hook(f: PanicHook) -> Result<(), HookFull> {
+        let slot = PANIC_HOOK_COUNT.fetch_add(1, Ordering::SeqCst);
+
+        if slot >= HOOK_CAPACITY {
+            PANIC_HOOK_COUNT.fetch_sub(1, Ordering::SeqCst);
+            return Err(HookFull);
+        }
+
+        PANIC_HOOKS[slot].store(f as *mut (), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Deregisters every panic hook previously added via [`add_panic_hook`].
+    pub fn clear_panic_hooks() {
+        PANIC_HOOK_COUNT.store(0, Ordering::SeqCst);
+
+        for hook in &PANIC_HOOKS {
+            hook.store(core::ptr::null_mut(), Ordering::SeqCst);
+        }
+    }
+
+    /// Runs every currently registered panic hook, in the order they were added.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn __run_panic_hooks(info: &core::panic::PanicInfo) {
+        let count = PANIC_HOOK_COUNT.load(Ordering::SeqCst).min(HOOK_CAPACITY);
+
+        for hook in &PANIC_HOOKS[..count] {
+            let ptr = hook.load(Ordering::SeqCst);
+
+            if !ptr.is_null() {
+                let f: PanicHook = unsafe { core::mem::transmute(ptr) };
+                f(info);
+            }
+        }
+    }
+
+    /// Set for the duration of the currently running panic handler. Used by [`__enter_panic`] to
+    /// detect a panic occurring while another panic is already being handled (e.g. the user's
+    /// handler itself panics), mirroring how the standard library forces an abort on a
+    /// panic-during-panic instead of recursing forever.
+    #[doc(hidden)]
+    pub static PANIC_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+    /// Marks the start of panic handling, aborting immediately if a panic is already being handled.
+    ///
+    /// Must be the very first thing the generated `#[panic_handler]` does, before running any user
+    /// handler or writing any diagnostics, so a panic re-entering the handler can never loop.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn __enter_panic() {
+        if PANIC_IN_PROGRESS.swap(true, Ordering::SeqCst) {
+            core::intrinsics::abort();
+        }
+    }
+
+    /// Writes the panic location (file, line, column) and, with the `nightly_message` feature, the
+    /// panic message to `sink`. Formatting errors are swallowed since there is nothing useful to do
+    /// with them from inside a panic handler.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn __write_panic_info(sink: &mut dyn core::fmt::Write, info: &core::panic::PanicInfo) {
+        let _ = match info.location() {
+            Some(location) => write!(
+                sink,
+                "panicked at {}:{}:{}",
+                location.file(),
+                location.line(),
+                location.column()
+            ),
+            None => write!(sink, "panicked at unknown location"),
+        };
+
+        #[cfg(feature = "nightly_message")]
+        {
+            let _ = write!(sink, ": {}", info.message());
+        }
+
+        let _ = write!(sink, "\n");
+    }
+
+    /// The action a panic handler takes once any custom handler and hooks have run.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PanicAction {
+        /// Spin forever behind a compiler fence.
+        Halt,
+        /// Call `core::intrinsics::abort()`.
+        Abort,
+        /// Reset the MCU via [`__panic_reset`].
+        Reset,
+    }
+
+    impl PanicAction {
+        /// The action `define_panic!` and `#[define_panic]` use when no action is given,
+        /// derived from the `abort_on_debug` / `abort_on_release` features the same way
+        /// `__default_panic` always has.
+        pub const DEFAULT: PanicAction = if cfg!(debug_assertions) {
+            if cfg!(feature = "abort_on_debug") {
+                PanicAction::Abort
+            } else {
+                PanicAction::Halt
+            }
+        } else if cfg!(feature = "abort_on_release") {
+            PanicAction::Abort
+        } else {
+            PanicAction::Halt
+        };
+    }
+
+    /// The action run for [`PanicAction::Reset`]. Weakly linked so a BSP crate can override it
+    /// with a real reset (e.g. `cortex_m::peripheral::SCB::sys_reset()`); falls back to aborting
+    /// if nothing overrides it.
+    #[doc(hidden)]
+    #[linkage = "weak"]
+    #[no_mangle]
+    extern "Rust" fn __panic_reset() -> ! {
+        core::intrinsics::abort()
+    }
+
+    /// Runs `action`, i.e. the fallback behavior once any custom handler and hooks have run.
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn __run_panic_action(action: PanicAction) -> ! {
+        match action {
+            PanicAction::Halt => loop {
+                atomic::compiler_fence(Ordering::SeqCst);
+            },
+            PanicAction::Abort => core::intrinsics::abort(),
+            PanicAction::Reset => __panic_reset(),
+        }
+    }
+
+    #[doc(hidden)]
+    #[inline(always)]
+    pub fn __default_panic() -> ! {
+        __run_panic_action(PanicAction::DEFAULT)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn noop_hook(_info: &core::panic::PanicInfo) {}
+
+        #[test]
+        fn panic_hooks_fill_capacity_then_err_and_clear_resets() {
+            clear_panic_hooks();
+
+            for _ in 0..HOOK_CAPACITY {
+                assert_eq!(add_panic_hook(noop_hook), Ok(()));
+            }
+            assert_eq!(add_panic_hook(noop_hook), Err(HookFull));
+
+            clear_panic_hooks();
+
+            assert_eq!(PANIC_HOOK_COUNT.load(Ordering::SeqCst), 0);
+            assert!(PANIC_HOOKS.iter().all(|hook| hook.load(Ordering::SeqCst).is_null()));
+            assert_eq!(add_panic_hook(noop_hook), Ok(()));
+
+            clear_panic_hooks();
+        }
+
+        #[test]
+        fn panic_action_default_matches_feature_selection() {
+            let expected = if cfg!(debug_assertions) {
+                if cfg!(feature = "abort_on_debug") {
+                    PanicAction::Abort
+                } else {
+                    PanicAction::Halt
+                }
+            } else if cfg!(feature = "abort_on_release") {
+                PanicAction::Abort
+            } else {
+                PanicAction::Halt
+            };
+
+            assert_eq!(PanicAction::DEFAULT, expected);
+        }
+
+        #[test]
+        fn enter_panic_sets_the_in_progress_flag() {
+            PANIC_IN_PROGRESS.store(false, Ordering::SeqCst);
+
+            __enter_panic();
+
+            assert!(PANIC_IN_PROGRESS.load(Ordering::SeqCst));
+
+            PANIC_IN_PROGRESS.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
 #[cfg(feature = "proc_macros")]
 #[doc(cfg(feature = "proc_macros"))]
 pub use panic_custom_proc_macros::define_panic;
@@ -83,7 +309,6 @@ pub use panic_custom_proc_macros::define_panic;
 #[cfg(not(feature = "proc_macros"))]
 #[doc(hidden)]
 pub mod no_macro {
-    use core::sync::atomic::{self, Ordering};
 
     /// This macro defines the behavior of the panic handler when procedural macros are not enabled.
     ///
@@ -116,60 +341,162 @@ pub mod no_macro {
     ///     42 // Still works + will be optimized by a compiler.
     /// });
     /// ```
+    ///
+    /// Prefix the arguments with `action: $action` (an expression of type
+    /// [`support::PanicAction`]) to pick what happens once the handler and all registered hooks
+    /// have run, instead of the feature-derived default:
+    ///
+    /// ```rust,ignore
+    /// use panic_custom::{define_panic, support::PanicAction};
+    ///
+    /// define_panic!(action: PanicAction::Reset, |info| {
+    ///     loop {}
+    /// });
+    /// ```
     #[macro_export]
     #[doc(cfg(not(feature = "proc_macros")))]
     macro_rules! define_panic {
+        (action: $action:expr, $sink_fn:expr, $panic_fn:expr) => {
+            $crate::define_panic_with_sink!(action: $action, $sink_fn, $panic_fn);
+        };
+        (action: $action:expr, $panic_fn:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__run_panic_hooks(info);
+
+                // Custom defined function
+                unsafe { $panic_fn(info); }
+
+                $crate::support::__run_panic_action($action)
+            }
+        };
+        (action: $action:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__run_panic_hooks(info);
+
+                $crate::support::__run_panic_action($action)
+            }
+        };
+        ($sink_fn:expr, $panic_fn:expr) => {
+            $crate::define_panic_with_sink!($sink_fn, $panic_fn);
+        };
         ($panic_fn:expr) => {
             #[inline(never)]
             #[panic_handler]
             fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__run_panic_hooks(info);
+
                 // Custom defined function
                 unsafe { $panic_fn(info); }
 
-                $crate::no_macro::__default_panic()
+                $crate::support::__default_panic()
             }
         };
         () => {
             #[inline(never)]
             #[panic_handler]
-            fn panic(_: &core::panic::PanicInfo) -> ! {
-                $crate::no_macro::__default_panic()
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__run_panic_hooks(info);
+
+                $crate::support::__default_panic()
             }
         }
     }
 
-    #[doc(hidden)]
-    #[inline(always)]
-    pub fn __default_panic() -> ! {
-        #[cfg(not(debug_assertions))]
-        {
-            #[cfg(not(feature = "abort_on_release"))] // Aborts.
-            {
-                loop {
-                    atomic::compiler_fence(Ordering::SeqCst); // Halting on debug.
-                }
+    /// Like [`define_panic!`], but first formats the panic location (and, with the `nightly_message`
+    /// feature, the panic message) into a user-supplied [`core::fmt::Write`] sink before running the
+    /// custom handler and falling back to the default action.
+    ///
+    /// `sink_fn` is an expression callable with no arguments that yields `&mut dyn core::fmt::Write`,
+    /// e.g. a function wrapping a semihosting port or a UART writer.
+    ///
+    /// ```rust,ignore
+    /// use panic_custom::define_panic_with_sink;
+    ///
+    /// fn sink() -> &'static mut dyn core::fmt::Write {
+    ///     // ... return a handle to your serial console ...
+    /// }
+    ///
+    /// define_panic_with_sink!(sink, |info| {
+    ///     loop {}
+    /// });
+    /// ```
+    ///
+    /// The handler closure can be omitted, in which case only the registered hooks and then the
+    /// default action run after the location (and message) have been written.
+    ///
+    /// As with [`define_panic!`], prefix the arguments with `action: $action` to override the
+    /// feature-derived default action.
+    #[macro_export]
+    #[doc(cfg(not(feature = "proc_macros")))]
+    macro_rules! define_panic_with_sink {
+        (action: $action:expr, $sink_fn:expr, $panic_fn:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__write_panic_info($sink_fn(), info);
+
+                $crate::support::__run_panic_hooks(info);
+
+                unsafe { $panic_fn(info); }
+
+                $crate::support::__run_panic_action($action)
             }
+        };
+        (action: $action:expr, $sink_fn:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
+
+                $crate::support::__write_panic_info($sink_fn(), info);
 
-            #[cfg(feature = "abort_on_release")] // Halts.
-            {
-                core::intrinsics::abort();
+                $crate::support::__run_panic_hooks(info);
+
+                $crate::support::__run_panic_action($action)
             }
-        } 
+        };
+        ($sink_fn:expr, $panic_fn:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
 
-        #[cfg(debug_assertions)]
-        {
-            #[cfg(not(feature = "abort_on_debug"))] // Aborts.
-            {
-                loop {
-                    atomic::compiler_fence(Ordering::SeqCst); // Halting on debug.
-                }
+                $crate::support::__write_panic_info($sink_fn(), info);
+
+                $crate::support::__run_panic_hooks(info);
+
+                unsafe { $panic_fn(info); }
+
+                $crate::support::__default_panic()
             }
+        };
+        ($sink_fn:expr) => {
+            #[inline(never)]
+            #[panic_handler]
+            fn panic(info: &core::panic::PanicInfo) -> ! {
+                $crate::support::__enter_panic();
 
-            #[cfg(feature = "abort_on_debug")] // Halts.
-            {
-                core::intrinsics::abort();
+                $crate::support::__write_panic_info($sink_fn(), info);
+
+                $crate::support::__run_panic_hooks(info);
+
+                $crate::support::__default_panic()
             }
-        }
+        };
     }
 }
 
@@ -3,8 +3,9 @@
 
 //! Small procedural macro crate for custom panic functions.
 //!
-//! This crate provides a `define_panic` procedural macro, which transforms a given function into a panic handler.
-//! No closures are allowed with this macro.
+//! This crate provides a `define_panic` procedural macro, which transforms a given function into a panic handler,
+//! and an `assert_no_panic` procedural macro, which statically proves that a function cannot panic.
+//! No closures are allowed with either macro.
 //!
 //! # Usage
 //!
@@ -26,7 +27,8 @@
 //! # Limitations
 //!
 //! - This macro only accepts functions as input. Closures are not allowed.
-//! - The panic handler function must diverge, i.e., it must return `!`.
+//! - The panic handler function does not need to diverge; the configured `PanicAction` always
+//!   runs once it returns.
 //! - Ensure that the panic handler function is properly defined and handles panics safely to avoid undefined behavior.
 //!
 //! # See Also
@@ -37,36 +39,59 @@
 //!
 //! - [The Rust Book - Panic Handling](https://doc.rust-lang.org/book/ch09-03-to-panic-or-not-to-panic.html)
 
+extern crate alloc;
+
+use alloc::format;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, FnArg, ItemFn, ReturnType, Type};
+use syn::{parse_macro_input, FnArg, ItemFn, Type};
 
 /// Defines the given function as a panic handler.
 ///
-/// This macro only accepts a function as an input. All functions must
-/// follow the same rule:
-///     `fn _some_name_(info: &PanicInfo) -> !;`
+/// This macro only accepts a function as an input, taking a single `&PanicInfo` parameter:
+///     `fn _some_name_(info: &PanicInfo);`
 ///
-/// # Examples
+/// The function's body runs once per panic; its return value, if any, is discarded. Once it
+/// returns, the configured action (see below) always runs — the body is not required to diverge.
+///
+/// ```rust
+/// use my_panic_macro::define_panic;
+///
+/// #[define_panic]
+/// fn my_panic_function(info: &PanicInfo) {
+///     // Custom panic handling logic
+/// }
+/// ```
+///
+/// An action to run once the function returns can be given as the attribute argument, as an
+/// expression of type `panic_custom::support::PanicAction`. It defaults to the feature-derived
+/// `PanicAction::DEFAULT` when omitted.
 ///
 /// ```rust
 /// use my_panic_macro::define_panic;
+/// use panic_custom::support::PanicAction;
 ///
-/// #[panic_handler]
-/// fn my_panic_function(info: &PanicInfo) -> ! {
+/// #[define_panic(PanicAction::Reset)]
+/// fn my_panic_function(info: &PanicInfo) {
 ///     // Custom panic handling logic
 /// }
 /// ```
 #[proc_macro_attribute]
-pub fn define_panic(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn define_panic(attr: TokenStream, input: TokenStream) -> TokenStream {
     let input_fn = parse_macro_input!(input as ItemFn);
 
+    let action = if attr.is_empty() {
+        quote! { ::panic_custom::support::PanicAction::DEFAULT }
+    } else {
+        let action_expr = parse_macro_input!(attr as syn::Expr);
+        quote! { #action_expr }
+    };
+
     // Extracting
     let vis =       &input_fn.vis;
     let attrs =     &input_fn.attrs;
     let block =     &input_fn.block;
     let inputs =    &input_fn.sig.inputs;
-    let output =    &input_fn.sig.output;
 
     // Ensuring the function has the correct signature.
     if let FnArg::Typed(arg) = inputs.first().unwrap() {
@@ -100,29 +125,111 @@ pub fn define_panic(_attr: TokenStream, input: TokenStream) -> TokenStream {
             .into();
     }
 
-    if let ReturnType::Type(_, ty) = output {
-        match ty.as_ref() {
-            Type::Never(_) => (),
-            _ => {
-                return syn::Error::new_spanned(
-                    output,
-                    "The panic handler function must diverge (return `!`).",
-                )
-                    .to_compile_error()
-                    .into();
-            },
-        }
-    }
-   
-
     let new_fn = quote! {
         #(#attrs)*
         #vis
         #[panic_handler]
-        fn panic(_: &::core::panic::PanicInfo) -> ! {
+        fn panic(info: &::core::panic::PanicInfo) -> ! {
+            ::panic_custom::support::__enter_panic();
+
+            ::panic_custom::support::__run_panic_hooks(info);
+
             unsafe {
-                #block
-            }    
+                #block;
+            }
+
+            ::panic_custom::support::__run_panic_action(#action)
+        }
+    };
+
+    new_fn.into()
+}
+
+/// Statically proves that the annotated function cannot panic, turning a reachable panic into a
+/// link error instead of a runtime one.
+///
+/// **This only proves anything under `panic = "unwind"`, and this crate's own generated handlers
+/// don't use that.** The guard's `Drop` impl below only runs if a panic on the annotated path
+/// actually unwinds the stack, i.e. the crate is built with `-C panic=unwind` and a working
+/// personality/unwind runtime is linked in. `define_panic!`, `define_panic_with_sink!`, and
+/// `#[define_panic]` never unwind — the installed `#[panic_handler]` runs directly in place of the
+/// panic and halts/aborts/resets without walking back up the call stack — and the large majority
+/// of `no_std` targets this crate is aimed at are built with `panic = "abort"` regardless. Under
+/// either of those, `Drop::drop` is never invoked on the panicking path, the undefined symbol is
+/// never referenced, and linking succeeds even for a function that unconditionally panics. Only
+/// rely on `#[assert_no_panic]` in a build actually configured for `panic = "unwind"` with
+/// unwinding enabled all the way through; it gives no guarantee otherwise.
+///
+/// # How it works
+///
+/// This uses the same trick as dtolnay's `no-panic` crate: a zero-sized guard is constructed at
+/// the start of the function and [`core::mem::forget`]-ten on the normal return path. Its `Drop`
+/// impl calls an `extern "C"` function that is declared but never defined, named after the
+/// annotated function. If the optimizer cannot prove every path through the function avoids
+/// unwinding, the guard's drop glue (and the reference to the undefined symbol) survives and
+/// the program fails to link, naming the function in the linker error. If the compiler proves
+/// the function cannot panic, the drop glue is dead code, the symbol reference is eliminated, and
+/// linking succeeds.
+///
+/// # Examples
+///
+/// ```rust
+/// use my_panic_macro::assert_no_panic;
+///
+/// #[assert_no_panic]
+/// fn add(a: u32, b: u32) -> u32 {
+///     a.wrapping_add(b)
+/// }
+/// ```
+///
+/// # Limitations
+///
+/// - This macro only accepts functions as input. Closures are not allowed.
+/// - Requires `panic = "unwind"` to prove anything; see the warning in the summary above.
+/// - A failure surfaces at link time, as an undefined reference to a symbol named after the
+///   function, rather than as a compile error.
+///
+/// # See Also
+///
+/// - [dtolnay/no-panic](https://github.com/dtolnay/no-panic): the crate this technique is taken from.
+#[proc_macro_attribute]
+pub fn assert_no_panic(_attr: TokenStream, input: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(input as ItemFn);
+
+    // Extracting
+    let vis =   &input_fn.vis;
+    let attrs = &input_fn.attrs;
+    let sig =   &input_fn.sig;
+    let block = &input_fn.block;
+
+    // Disambiguated with a per-expansion counter: `sig.ident` alone collides between two
+    // `#[assert_no_panic]` functions of the same name in different modules, which would make the
+    // resulting linker error ambiguous about which one actually panics.
+    static TRIGGER_COUNTER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+    let trigger_id = TRIGGER_COUNTER.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    let trigger_symbol = format!("__assert_no_panic_{}_{}", sig.ident, trigger_id);
+
+    let new_fn = quote! {
+        #(#attrs)*
+        #vis #sig {
+            struct __AssertNoPanic;
+
+            impl ::core::ops::Drop for __AssertNoPanic {
+                #[inline(always)]
+                fn drop(&mut self) {
+                    extern "C" {
+                        #[link_name = #trigger_symbol]
+                        fn trigger() -> !;
+                    }
+
+                    unsafe { trigger() }
+                }
+            }
+
+            let __assert_no_panic_guard = __AssertNoPanic;
+            let __assert_no_panic_result = (move || #block)();
+            ::core::mem::forget(__assert_no_panic_guard);
+            __assert_no_panic_result
         }
     };
 